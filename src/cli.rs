@@ -9,13 +9,24 @@
 
 use std::{
     net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
     sync::LazyLock,
+    time::Duration,
 };
 
 use anyhow::{bail, Result};
 use clap::Parser;
+use serde_json::Value;
 
-use knxkit::{connection::remote, project::Project};
+use knxkit::{
+    connection::remote,
+    core::{address::GroupAddress, dpt::DPT, DataPoint},
+    project::{Project, ProjectExt},
+};
+
+use knxkit_dpt::project::ProjectExtDPT;
+
+use crate::mapping::Mapping;
 
 fn parse_local(v: &str) -> Result<Ipv4Addr> {
     let local = if v != "auto" {
@@ -35,6 +46,32 @@ fn parse_project(v: &str) -> Result<Project> {
     Ok(Project::open(v)?)
 }
 
+fn parse_map(v: &str) -> Result<Mapping> {
+    Mapping::open(v)
+}
+
+/// Parses a duration given as `<number><unit>`, where the unit is `ms`, `s`
+/// (default), `m` or `h`.
+fn parse_duration(v: &str) -> Result<Duration> {
+    let v = v.trim();
+    let (value, unit) = match v.find(|c: char| c.is_alphabetic()) {
+        Some(pos) => (&v[..pos], &v[pos..]),
+        None => (v, "s"),
+    };
+
+    let value: f64 = value.trim().parse()?;
+
+    let seconds = match unit {
+        "ms" => value / 1000.0,
+        "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        other => bail!("unknown duration unit {other:?}"),
+    };
+
+    Ok(Duration::from_secs_f64(seconds))
+}
+
 #[derive(Parser, Debug, Clone)]
 pub struct Cli {
     #[arg(short = 'l', long = "local")]
@@ -48,6 +85,11 @@ pub struct Cli {
     #[arg(value_parser = parse_project)]
     pub project: Option<Project>,
 
+    /// Declarative TOML group-address mapping, used instead of `--project`.
+    #[arg(long, conflicts_with = "project")]
+    #[arg(value_parser = parse_map)]
+    pub map: Option<Mapping>,
+
     #[arg(long)]
     #[arg(value_parser = remote::parse_remote)]
     pub remote: remote::RemoteSpec,
@@ -58,11 +100,160 @@ pub struct Cli {
     #[arg(long, default_value = "1883")]
     pub mqtt_port: u16,
 
+    #[arg(long)]
+    pub mqtt_username: Option<String>,
+
+    #[arg(long, requires = "mqtt_username")]
+    pub mqtt_password: Option<String>,
+
+    /// Connect to the broker over TLS (rustls).
+    #[arg(long)]
+    pub mqtt_tls: bool,
+
+    /// PEM file with the CA certificate that signed the broker certificate.
+    #[arg(long, requires = "mqtt_tls")]
+    pub mqtt_ca_cert: Option<PathBuf>,
+
+    /// PEM client certificate chain for mutual TLS (requires `--mqtt-client-key`).
+    #[arg(long, requires = "mqtt_tls", requires = "mqtt_client_key")]
+    pub mqtt_client_cert: Option<PathBuf>,
+
+    /// PEM client private key for mutual TLS (requires `--mqtt-client-cert`).
+    #[arg(long, requires = "mqtt_tls", requires = "mqtt_client_cert")]
+    pub mqtt_client_key: Option<PathBuf>,
+
     #[arg(long, default_value = "knx/group")]
     pub mqtt_prefix: String,
 
+    /// Publish Home Assistant MQTT discovery `config` topics for every group in
+    /// the ETS project (or `--map`) whose DPT maps to a Home Assistant
+    /// component, on every broker (re)connect.
+    #[arg(long)]
+    pub discovery: bool,
+
+    /// Topic prefix Home Assistant listens on for discovery messages.
+    #[arg(long, default_value = "homeassistant")]
+    pub discovery_prefix: String,
+
+    /// In addition to the JSON envelope, publish the decoded scalar, raw hex and
+    /// unit as plain retained payloads on `{group}/value`, `/raw` and `/unit`.
+    /// Writes on `{group}/value` and `{group}/set` are accepted whether or not
+    /// this flag is set.
+    #[arg(long)]
+    pub flat_topics: bool,
+
+    /// Topic the bridge publishes its `online`/`offline` availability on.
+    /// Defaults to `{mqtt_prefix}/status`.
+    #[arg(long)]
+    pub availability_topic: Option<String>,
+
+    /// Request every known group once at startup.
+    #[arg(long)]
+    pub initial_request: bool,
+
+    /// Minimum spacing between consecutive group requests on the bus.
+    #[arg(long, default_value = "1s", value_parser = parse_duration)]
+    pub initial_request_delay: Duration,
+
+    /// Default refresh interval for periodic polling. Groups without a
+    /// per-group interval are polled at this rate; when unset, only groups
+    /// with a per-group interval (from `--map`) are polled.
+    #[arg(long, value_parser = parse_duration)]
+    pub poll_interval: Option<Duration>,
+
     #[arg(long)]
     pub ignore_unknown: bool,
 }
 
+impl Cli {
+    /// The resolved availability topic (`--availability-topic` or the
+    /// `{mqtt_prefix}/status` default).
+    pub fn availability_topic(&self) -> String {
+        self.availability_topic
+            .clone()
+            .unwrap_or_else(|| format!("{}/status", self.mqtt_prefix))
+    }
+
+    /// The configured DPT source: the ETS project, the TOML mapping, or neither.
+    pub fn source(&self) -> Source<'_> {
+        match &self.map {
+            Some(map) => Source::Map(map),
+            None => Source::Project(self.project.as_ref()),
+        }
+    }
+}
+
+/// Address and name of a single group, regardless of where it was configured.
+pub struct GroupInfo {
+    pub address: GroupAddress,
+    pub name: String,
+}
+
+/// A unified view over the ETS project and the declarative [`Mapping`]. Both
+/// provide the same `group_dpt` / `group_dpt_unit` / `group_json` lookups, so
+/// the rest of the bridge doesn't care which one is in use.
+pub enum Source<'a> {
+    Project(Option<&'a Project>),
+    Map(&'a Mapping),
+}
+
+impl Source<'_> {
+    pub fn group_dpt(&self, group: GroupAddress) -> Option<DPT> {
+        match self {
+            Source::Project(project) => project.group_dpt(group),
+            Source::Map(map) => map.group_dpt(group),
+        }
+    }
+
+    pub fn group_dpt_unit(&self, group: GroupAddress) -> Option<String> {
+        match self {
+            Source::Project(project) => project.group_dpt_unit(group).map(|unit| unit.to_string()),
+            Source::Map(map) => map.group_dpt_unit(group),
+        }
+    }
+
+    pub fn group_json(&self, group: GroupAddress, data: &DataPoint) -> Option<Value> {
+        match self {
+            Source::Project(project) => project.group_json(group, data),
+            Source::Map(map) => map.group_json(group, data),
+        }
+    }
+
+    /// Per-group poll interval, if the source defines one. The ETS project has
+    /// no such field, so only the [`Mapping`] can override the global default.
+    pub fn group_poll_interval(&self, group: GroupAddress) -> Option<Duration> {
+        match self {
+            Source::Project(_) => None,
+            Source::Map(map) => map.group_poll_interval(group),
+        }
+    }
+
+    /// All configured groups (address and name).
+    pub fn groups(&self) -> Vec<GroupInfo> {
+        match self {
+            Source::Project(project) => project
+                .map(|project| {
+                    project
+                        .groups
+                        .groups
+                        .iter()
+                        .map(|group| GroupInfo {
+                            address: group.address,
+                            name: group.name.clone(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Source::Map(map) => map
+                .groups()
+                .iter()
+                .map(|group| GroupInfo {
+                    address: group.address,
+                    name: group.name.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
 pub static CLI: LazyLock<Cli> = LazyLock::new(Cli::parse);
@@ -0,0 +1,222 @@
+// Copyright (c) 2024 Alexey Aristov <aav@acm.org> and others
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at http://
+// www.eclipse.org/legal/epl-2.0, or the GNU General Public License, version 3
+// which is available at https://www.gnu.org/licenses/gpl-3.0.en.html.
+//
+// SPDX-License-Identifier: EPL-2.0 OR GPL-3.0
+
+//! Home Assistant MQTT discovery.
+//!
+//! On every broker (re)connect the bridge walks every group in the ETS project
+//! (or the `--map` table) and publishes a retained `config` topic under
+//! [`Cli::discovery_prefix`] so each KNX group address whose DPT maps to a Home
+//! Assistant component shows up as an entity without any hand-written YAML;
+//! groups with no DPT or an unmapped DPT are skipped. The discovery entities
+//! talk to the very same `{mqtt_prefix}/{group}` topic the bridge already
+//! publishes and subscribes on, reading the JSON `value` field of
+//! [`MqttGroupMessageOut`] through state templates and writing the `value`
+//! field of [`MqttGroupMessageIn`] through fixed JSON payloads or command
+//! templates.
+
+use rumqttc::v5::{mqttbytes::QoS, AsyncClient};
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use knxkit::core::address::GroupAddress;
+
+use crate::cli::CLI;
+
+/// Home Assistant component a KNX group is exposed as.
+enum Component {
+    Switch,
+    BinarySensor,
+    Light,
+    Sensor,
+    Climate,
+    Cover,
+}
+
+impl Component {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Component::Switch => "switch",
+            Component::BinarySensor => "binary_sensor",
+            Component::Light => "light",
+            Component::Sensor => "sensor",
+            Component::Climate => "climate",
+            Component::Cover => "cover",
+        }
+    }
+}
+
+/// Splits a DPT string such as `"9.001"` into its `(main, sub)` parts.
+fn dpt_parts(dpt: &str) -> Option<(u32, u32)> {
+    let (main, sub) = dpt.split_once('.')?;
+    Some((main.parse().ok()?, sub.parse().ok()?))
+}
+
+/// Maps a DPT to the Home Assistant component that best represents it, or `None`
+/// for DPTs we don't expose automatically.
+fn component_for(main: u32, sub: u32) -> Option<Component> {
+    match (main, sub) {
+        (1, 1) => Some(Component::Switch),
+        // step (1.007), up/down (1.008), open/close (1.009) and start/stop
+        // (1.010) all describe a moving cover
+        (1, 7..=10) => Some(Component::Cover),
+        (1, _) => Some(Component::BinarySensor),
+        // 3.007 is 4-bit relative control dimming (direction + 3-bit step), not
+        // a 0..=100 percent value, and Home Assistant has no native entity for
+        // relative dimming, so it is intentionally not auto-discovered. Only
+        // 5.001 scaling, which does carry a percent, is exposed as a brightness
+        // light.
+        (3, 7) => None,
+        (5, 1) => Some(Component::Light),
+        (9, _) | (13, _) | (14, _) => Some(Component::Sensor),
+        (20, 102) => Some(Component::Climate),
+        _ => None,
+    }
+}
+
+/// The state/command topic this bridge already uses for `group`.
+fn group_topic(group: GroupAddress) -> String {
+    format!("{}/{}", CLI.mqtt_prefix, group)
+}
+
+/// A stable, MQTT-safe identifier derived from a group address (`1/2/3` →
+/// `1_2_3`).
+fn object_id(group: GroupAddress) -> String {
+    group.to_string().replace('/', "_")
+}
+
+/// Builds the discovery payload for a single group, or `None` when the group has
+/// no DPT or its DPT isn't mapped to a component.
+fn config_payload(group: GroupAddress, name: &str) -> Option<(Component, Value)> {
+    let project = CLI.source();
+
+    let dpt = project.group_dpt(group)?;
+    let (main, sub) = dpt_parts(&dpt.to_string())?;
+    let component = component_for(main, sub)?;
+
+    let topic = group_topic(group);
+    let unit = project.group_dpt_unit(group);
+
+    let mut payload = json!({
+        "name": name,
+        "unique_id": format!("knx2mqtt_{}", object_id(group)),
+        "object_id": object_id(group),
+    });
+    let map = payload.as_object_mut().unwrap();
+
+    match component {
+        Component::Switch => {
+            map.insert("state_topic".into(), topic.clone().into());
+            map.insert("command_topic".into(), topic.into());
+            map.insert("value_template".into(), "{{ value_json.value }}".into());
+            map.insert("state_on".into(), true.into());
+            map.insert("state_off".into(), false.into());
+            map.insert("payload_on".into(), r#"{"value": true}"#.into());
+            map.insert("payload_off".into(), r#"{"value": false}"#.into());
+        }
+
+        Component::BinarySensor => {
+            map.insert("state_topic".into(), topic.into());
+            map.insert("value_template".into(), "{{ value_json.value }}".into());
+            map.insert("payload_on".into(), true.into());
+            map.insert("payload_off".into(), false.into());
+        }
+
+        Component::Light => {
+            map.insert("schema".into(), "template".into());
+            map.insert("state_topic".into(), topic.clone().into());
+            map.insert("command_topic".into(), topic.into());
+            // 5.001 scaling values are 0..=100 percent in the decoded JSON
+            map.insert(
+                "state_template".into(),
+                "{{ 'on' if value_json.value | int > 0 else 'off' }}".into(),
+            );
+            map.insert(
+                "brightness_template".into(),
+                "{{ (value_json.value | int * 255 / 100) | int }}".into(),
+            );
+            map.insert(
+                "command_on_template".into(),
+                r#"{"value": {{ (brightness | default(255) | int * 100 / 255) | int }}}"#.into(),
+            );
+            map.insert("command_off_template".into(), r#"{"value": 0}"#.into());
+        }
+
+        Component::Sensor => {
+            map.insert("state_topic".into(), topic.into());
+            map.insert("value_template".into(), "{{ value_json.value }}".into());
+            if let Some(unit) = unit {
+                map.insert("unit_of_measurement".into(), unit.to_string().into());
+            }
+        }
+
+        Component::Climate => {
+            map.insert("mode_state_topic".into(), topic.clone().into());
+            map.insert("mode_command_topic".into(), topic.into());
+            map.insert(
+                "mode_state_template".into(),
+                "{{ value_json.value }}".into(),
+            );
+            map.insert(
+                "mode_command_template".into(),
+                r#"{"value": "{{ value }}"}"#.into(),
+            );
+        }
+
+        Component::Cover => {
+            map.insert("state_topic".into(), topic.clone().into());
+            map.insert("command_topic".into(), topic.into());
+            map.insert("value_template".into(), "{{ value_json.value }}".into());
+            map.insert("state_open".into(), false.into());
+            map.insert("state_closed".into(), true.into());
+            map.insert("payload_open".into(), r#"{"value": false}"#.into());
+            map.insert("payload_close".into(), r#"{"value": true}"#.into());
+            map.insert("payload_stop".into(), Value::Null);
+        }
+    }
+
+    Some((component, payload))
+}
+
+/// Publishes a retained discovery `config` topic for every mapped group.
+pub async fn publish(client: &AsyncClient) {
+    let groups = CLI.source().groups();
+
+    if groups.is_empty() {
+        warn!("discovery requested but no project or map is configured");
+        return;
+    }
+
+    let mut published = 0usize;
+
+    for group in groups {
+        let Some((component, payload)) = config_payload(group.address, &group.name) else {
+            continue;
+        };
+
+        let topic = format!(
+            "{}/{}/{}/config",
+            CLI.discovery_prefix,
+            component.as_str(),
+            object_id(group.address)
+        );
+
+        let payload = serde_json::to_string(&payload).expect("json serialize");
+
+        if let Err(error) = client
+            .publish(topic, QoS::AtLeastOnce, true, payload)
+            .await
+        {
+            warn!(%error, group = %group.address, "cannot publish discovery config");
+        } else {
+            published += 1;
+        }
+    }
+
+    debug!("published {published} discovery configs");
+}
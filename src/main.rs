@@ -12,18 +12,21 @@ use std::{collections::VecDeque, str::FromStr, time::Duration};
 use adaptive_backoff::prelude::{Backoff, BackoffBuilder, ExponentialBackoffBuilder};
 use anyhow::Result;
 use cli::CLI;
-use rumqttc::v5::{
-    mqttbytes::{
-        v5::{Filter, Packet, Publish},
-        QoS,
+use rumqttc::{
+    v5::{
+        mqttbytes::{
+            v5::{Filter, LastWill, Packet, Publish},
+            QoS,
+        },
+        AsyncClient, Event, EventLoop, MqttOptions, Outgoing,
     },
-    AsyncClient, Event, EventLoop, MqttOptions,
+    TlsConfiguration, Transport,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::{
     signal::unix::{signal, Signal, SignalKind},
-    time::sleep,
+    time::{sleep, sleep_until, Instant},
 };
 use tracing::{debug, error, warn};
 
@@ -36,12 +39,92 @@ use knxkit::{
         tpdu::TPDU,
         DataPoint,
     },
-    project::ProjectExt,
 };
 
-use knxkit_dpt::{generic, project::ProjectExtDPT};
+use knxkit_dpt::generic;
 
 mod cli;
+mod discovery;
+mod mapping;
+mod poll;
+
+/// Builds the rustls transport configuration for the broker connection from the
+/// `--mqtt-ca-cert` / `--mqtt-client-cert` / `--mqtt-client-key` options.
+///
+/// When no CA certificate is supplied the platform's native root store is used,
+/// so a broker with a publicly trusted certificate works out of the box.
+fn tls_configuration() -> Result<TlsConfiguration> {
+    use std::{fs, io::BufReader};
+
+    use rustls::{ClientConfig, RootCertStore};
+
+    let mut roots = RootCertStore::empty();
+
+    if let Some(ca) = &CLI.mqtt_ca_cert {
+        let mut reader = BufReader::new(fs::File::open(ca)?);
+        for cert in rustls_pemfile::certs(&mut reader) {
+            roots.add(cert?)?;
+        }
+    } else {
+        for cert in rustls_native_certs::load_native_certs().certs {
+            let _ = roots.add(cert);
+        }
+    }
+
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+
+    let config = match (&CLI.mqtt_client_cert, &CLI.mqtt_client_key) {
+        (Some(cert), Some(key)) => {
+            let mut cert_reader = BufReader::new(fs::File::open(cert)?);
+            let chain = rustls_pemfile::certs(&mut cert_reader).collect::<Result<Vec<_>, _>>()?;
+
+            let mut key_reader = BufReader::new(fs::File::open(key)?);
+            let key = rustls_pemfile::private_key(&mut key_reader)?
+                .ok_or_else(|| anyhow::anyhow!("no private key in {}", key.display()))?;
+
+            builder.with_client_auth_cert(chain, key)?
+        }
+
+        _ => builder.with_no_client_auth(),
+    };
+
+    Ok(TlsConfiguration::Rustls(std::sync::Arc::new(config)))
+}
+
+/// Renders a JSON scalar as a plain flat-topic payload: strings drop their
+/// quotes, everything else uses its JSON representation.
+fn scalar_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Parses a `{group}/value` or `{group}/set` payload, which may be either the
+/// JSON [`MqttGroupMessageIn`] envelope or a bare scalar (`true`, `21.5`, `"on"`
+/// or an unquoted token).
+fn parse_flat_payload(payload: &[u8]) -> Result<MqttGroupMessageIn> {
+    match serde_json::from_slice::<Value>(payload) {
+        // an object is treated as the existing envelope
+        Ok(Value::Object(_)) => Ok(serde_json::from_slice::<MqttGroupMessageIn>(payload)?),
+
+        // any other valid JSON value is a bare scalar
+        Ok(value) => Ok(MqttGroupMessageIn {
+            raw: None,
+            value: Some(value),
+        }),
+
+        // not valid JSON: take the payload verbatim as a string scalar
+        Err(_) => Ok(MqttGroupMessageIn {
+            raw: None,
+            value: Some(Value::String(String::from_utf8_lossy(payload).into_owned())),
+        }),
+    }
+}
+
+/// Availability payloads published on [`Cli::availability_topic`].
+const PAYLOAD_ONLINE: &str = "online";
+const PAYLOAD_OFFLINE: &str = "offline";
 
 struct Mqtt {
     mqtt_client: AsyncClient,
@@ -70,7 +153,25 @@ struct MqttGroupMessageIn {
 
 impl Mqtt {
     fn new() -> Self {
-        let options = MqttOptions::new("knx2mqtt", &CLI.mqtt_host, cli::CLI.mqtt_port);
+        let mut options = MqttOptions::new("knx2mqtt", &CLI.mqtt_host, cli::CLI.mqtt_port);
+
+        // a username-only login is valid (token/anonymous-password brokers);
+        // a password without a username is rejected by clap (`requires`)
+        if let Some(username) = &CLI.mqtt_username {
+            options.set_credentials(username, CLI.mqtt_password.clone().unwrap_or_default());
+        }
+
+        if CLI.mqtt_tls {
+            options.set_transport(Transport::Tls(tls_configuration().expect("tls configuration")));
+        }
+
+        options.set_last_will(LastWill::new(
+            CLI.availability_topic(),
+            PAYLOAD_OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+
         let (mqtt_client, mqtt_event_loop) = AsyncClient::new(options, 16);
 
         let interrupt = signal(SignalKind::interrupt()).unwrap();
@@ -82,7 +183,7 @@ impl Mqtt {
         }
     }
 
-    fn handle_knx(&self, cemi: std::sync::Arc<CEMI>) -> Result<Option<(String, String)>> {
+    fn handle_knx(&self, cemi: std::sync::Arc<CEMI>) -> Result<Vec<(String, String)>> {
         if let (
             TPDU::DataGroup(APDU {
                 service,
@@ -95,7 +196,7 @@ impl Mqtt {
             let service = *service;
             let group = *group;
 
-            let project = CLI.project.as_ref();
+            let project = CLI.source();
 
             if service == Service::GroupValueWrite || service == Service::GroupValueResponse {
                 let mut message = MqttGroupMessageOut {
@@ -115,24 +216,58 @@ impl Mqtt {
                     message.value = Some(value);
                 }
 
-                let message = serde_json::to_string(&message).expect("json serialize");
+                let base = format!("{}/{}", CLI.mqtt_prefix, group);
+                let envelope = serde_json::to_string(&message).expect("json serialize");
+
+                let mut out = Vec::with_capacity(1);
+                out.push((base.clone(), envelope));
 
-                return Ok(Some((format!("{}/{}", CLI.mqtt_prefix, group), message)));
+                if CLI.flat_topics {
+                    out.push((format!("{base}/raw"), message.raw.clone()));
+
+                    if let Some(value) = &message.value {
+                        out.push((format!("{base}/value"), scalar_to_string(value)));
+                    }
+
+                    if let Some(unit) = &message.unit {
+                        out.push((format!("{base}/unit"), unit.clone()));
+                    }
+                }
+
+                return Ok(out);
             }
         }
 
-        Ok(None)
+        Ok(Vec::new())
     }
 
     fn handle_mqtt(&self, publish: Publish) -> Result<Option<(GroupAddress, DataPoint)>> {
         if publish.topic.starts_with(CLI.mqtt_prefix.as_bytes()) {
-            let project = CLI.project.as_ref();
+            let project = CLI.source();
+
+            let rest = String::from_utf8_lossy(&publish.topic[CLI.mqtt_prefix.len() + 1..]);
+
+            // `{group}/value` and `{group}/set` carry a bare scalar or the JSON
+            // envelope; `{group}/raw` and `{group}/unit` are our own flat
+            // outputs and are never treated as commands.
+            let (group_str, flat) = if let Some(group) = rest
+                .strip_suffix("/value")
+                .or_else(|| rest.strip_suffix("/set"))
+            {
+                (group, true)
+            } else if rest.ends_with("/raw") || rest.ends_with("/unit") {
+                return Ok(None);
+            } else {
+                (rest.as_ref(), false)
+            };
 
-            let group = GroupAddress::from_str(&String::from_utf8_lossy(
-                &publish.topic[CLI.mqtt_prefix.len() + 1..],
-            ))?;
+            let group = GroupAddress::from_str(group_str)?;
 
-            let message = serde_json::from_slice::<MqttGroupMessageIn>(&publish.payload)?;
+            let message = if flat {
+                parse_flat_payload(&publish.payload)?
+            } else {
+                serde_json::from_slice::<MqttGroupMessageIn>(&publish.payload)?
+            };
             let dpt = project.group_dpt(group);
 
             if dpt.is_none() && CLI.ignore_unknown {
@@ -183,35 +318,82 @@ impl Mqtt {
         }
     }
 
-    /// returns true if outer loop should continue
-    async fn run_loop(&mut self, connection: &mut impl KnxBusConnection) -> bool {
+    /// Re-establishes broker-side state on every (re)connect: the broker's
+    /// retained store and our subscriptions are gone after a restart, and the
+    /// v5 event loop reconnects transparently without restarting `run_loop`.
+    async fn on_connect(&self) {
         let mut filter = Filter::new(format!("{}/#", CLI.mqtt_prefix), QoS::AtLeastOnce);
         filter.nolocal = true;
 
-        self.mqtt_client.subscribe_many([filter]).await.unwrap();
+        if let Err(error) = self.mqtt_client.subscribe_many([filter]).await {
+            warn!(%error, "cannot subscribe");
+        }
+
+        if CLI.discovery {
+            discovery::publish(&self.mqtt_client).await;
+        }
+
+        self.publish_online().await;
+    }
+
+    /// Marks the bridge available. Published both on broker (re)connect and on
+    /// KNX bus (re)establishment, since availability requires both links.
+    async fn publish_online(&self) {
+        if let Err(error) = self
+            .mqtt_client
+            .publish(CLI.availability_topic(), QoS::AtLeastOnce, true, PAYLOAD_ONLINE)
+            .await
+        {
+            warn!(%error, "cannot publish availability");
+        }
+    }
+
+    /// returns true if outer loop should continue
+    async fn run_loop(&mut self, connection: &mut impl KnxBusConnection) -> bool {
+        // the KNX bus is up on entry; the broker link is restored via ConnAck
+        self.publish_online().await;
 
         let mut request_queue = VecDeque::new();
 
         if CLI.initial_request {
-            if let Some(project) = CLI.project.as_ref() {
-                for group in project.groups.groups.iter() {
-                    request_queue.push_back(group.address);
-                }
-
-                debug!("initial request for {} groups", request_queue.len());
+            for group in CLI.source().groups() {
+                request_queue.push_back(group.address);
             }
+
+            debug!("initial request for {} groups", request_queue.len());
         }
 
+        let mut poller = poll::Poller::new();
+
         loop {
             tokio::select! {
                 _ = self.interrupt.recv() => {
+                    // a clean DISCONNECT makes the broker discard the will, so
+                    // publish the offline state explicitly before leaving
+                    _ = self
+                        .mqtt_client
+                        .publish(CLI.availability_topic(), QoS::AtLeastOnce, true, PAYLOAD_OFFLINE)
+                        .await;
                     _ = self.mqtt_client.disconnect().await;
+
+                    // flush the pending publish and disconnect through the event loop
+                    while !matches!(
+                        self.mqtt_event_loop.poll().await,
+                        Ok(Event::Outgoing(Outgoing::Disconnect)) | Err(_)
+                    ) {}
+
                     debug!("interrupt signal, terminating");
                     break false;
                 }
 
                 poll = self.mqtt_event_loop.poll() => {
                     match poll {
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            // (re)connected to the broker: restore subscriptions,
+                            // retained discovery configs and the online state
+                            self.on_connect().await;
+                        }
+
                         Ok(Event::Incoming(Packet::Publish(publish))) if !publish.retain=> {
                             match self.handle_mqtt(publish) {
                                 Ok(Some((group_address, data_point))) => {
@@ -252,17 +434,30 @@ impl Mqtt {
                     }
                 }
 
+                _ = sleep_until(poller.as_ref().map_or_else(
+                    || Instant::now() + Duration::from_secs(3600),
+                    poll::Poller::next_due,
+                )) => {
+                    if let Some(poller) = poller.as_mut() {
+                        for group in poller.take_due(Instant::now()) {
+                            // skip groups whose previous request is still queued
+                            // so a slow drain can't grow the queue without bound
+                            if !request_queue.contains(&group) {
+                                request_queue.push_back(group);
+                            }
+                        }
+                    }
+                }
+
                 recv = connection.recv() => {
                     if let Some(cemi) = recv {
                         match self.handle_knx(cemi) {
-                            Ok(Some((topic, message))) => {
-                                debug!(topic, message, "mqtt message");
-
-                                self.mqtt_client.publish(topic, QoS::AtLeastOnce, true, message,).await.unwrap();
-                            }
+                            Ok(messages) => {
+                                for (topic, message) in messages {
+                                    debug!(topic, message, "mqtt message");
 
-                            Ok(None) => {
-                                // nothing to forward
+                                    self.mqtt_client.publish(topic, QoS::AtLeastOnce, true, message,).await.unwrap();
+                                }
                             }
 
                             Err(error) => {
@@ -271,6 +466,12 @@ impl Mqtt {
                         }
                     } else {
                         debug!("bus connection closed");
+                        // the broker is still up, so the last will won't fire:
+                        // flag the outage ourselves before the reconnect backoff
+                        _ = self
+                            .mqtt_client
+                            .publish(CLI.availability_topic(), QoS::AtLeastOnce, true, PAYLOAD_OFFLINE)
+                            .await;
                         break true;
                     }
                 }
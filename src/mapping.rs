@@ -0,0 +1,133 @@
+// Copyright (c) 2024 Alexey Aristov <aav@acm.org> and others
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at http://
+// www.eclipse.org/legal/epl-2.0, or the GNU General Public License, version 3
+// which is available at https://www.gnu.org/licenses/gpl-3.0.en.html.
+//
+// SPDX-License-Identifier: EPL-2.0 OR GPL-3.0
+
+//! Declarative TOML group-address mapping.
+//!
+//! An ETS `.knxproj` is the canonical source of the group-address/DPT table, but
+//! not everyone has (or wants to ship) one. `--map <file.toml>` loads the same
+//! information from a hand-written, version-controllable table:
+//!
+//! ```toml
+//! [groups."1/2/3"]
+//! dpt = "1.001"
+//! name = "Kitchen light"
+//!
+//! [groups."0/0/1"]
+//! dpt = "9.001"
+//! name = "Living room temperature"
+//! unit = "°C"
+//! ```
+//!
+//! It exposes the same `group_dpt` / `group_dpt_unit` / `group_json` lookups the
+//! ETS [`Project`] does (see [`crate::cli::Source`]), so `handle_knx` /
+//! `handle_mqtt` work unchanged whichever source is configured.
+
+use std::{collections::HashMap, path::Path, str::FromStr, time::Duration};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use knxkit::core::{address::GroupAddress, dpt::DPT, DataPoint};
+
+use knxkit_dpt::generic;
+
+/// A single mapped group address.
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub address: GroupAddress,
+    pub name: String,
+    pub dpt: DPT,
+    pub unit: Option<String>,
+    pub poll_interval: Option<Duration>,
+}
+
+/// A declarative mapping loaded from a TOML file.
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    groups: Vec<Group>,
+    index: HashMap<GroupAddress, usize>,
+}
+
+#[derive(Deserialize)]
+struct RawMapping {
+    groups: HashMap<String, RawGroup>,
+}
+
+#[derive(Deserialize)]
+struct RawGroup {
+    dpt: String,
+    name: Option<String>,
+    unit: Option<String>,
+    /// Per-group poll interval in seconds.
+    poll: Option<f64>,
+}
+
+impl Mapping {
+    /// Loads a mapping from a TOML file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read map {}", path.display()))?;
+
+        let raw: RawMapping = toml::from_str(&text)
+            .with_context(|| format!("cannot parse map {}", path.display()))?;
+
+        let mut groups = Vec::with_capacity(raw.groups.len());
+        let mut index = HashMap::with_capacity(raw.groups.len());
+
+        for (address, entry) in raw.groups {
+            let address = GroupAddress::from_str(&address)
+                .with_context(|| format!("invalid group address {address:?}"))?;
+            let dpt = DPT::from_str(&entry.dpt)
+                .with_context(|| format!("invalid dpt {:?} for {address}", entry.dpt))?;
+
+            index.insert(address, groups.len());
+            groups.push(Group {
+                address,
+                name: entry.name.unwrap_or_else(|| address.to_string()),
+                dpt,
+                unit: entry.unit,
+                poll_interval: entry.poll.map(Duration::from_secs_f64),
+            });
+        }
+
+        Ok(Self { groups, index })
+    }
+
+    /// All mapped groups, in no particular order.
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+
+    fn get(&self, group: GroupAddress) -> Option<&Group> {
+        self.index.get(&group).map(|&i| &self.groups[i])
+    }
+
+    pub fn group_dpt(&self, group: GroupAddress) -> Option<DPT> {
+        self.get(group).map(|g| g.dpt)
+    }
+
+    pub fn group_dpt_unit(&self, group: GroupAddress) -> Option<String> {
+        let group = self.get(group)?;
+        group
+            .unit
+            .clone()
+            .or_else(|| group.dpt.unit().map(|unit| unit.to_string()))
+    }
+
+    pub fn group_poll_interval(&self, group: GroupAddress) -> Option<Duration> {
+        self.get(group).and_then(|g| g.poll_interval)
+    }
+
+    pub fn group_json(&self, group: GroupAddress, data: &DataPoint) -> Option<Value> {
+        let dpt = self.group_dpt(group)?;
+        generic::decode(dpt, data).ok().map(|generic| generic.to_json())
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright (c) 2024 Alexey Aristov <aav@acm.org> and others
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at http://
+// www.eclipse.org/legal/epl-2.0, or the GNU General Public License, version 3
+// which is available at https://www.gnu.org/licenses/gpl-3.0.en.html.
+//
+// SPDX-License-Identifier: EPL-2.0 OR GPL-3.0
+
+//! Periodic group polling.
+//!
+//! KNX devices only broadcast the values they choose to; sensors that answer on
+//! request but never push stay stale in MQTT. The poller re-enqueues a
+//! `group_request` for each group on its own schedule — a per-group interval
+//! from the [`Mapping`](crate::mapping::Mapping), falling back to the global
+//! `--poll-interval` default. Requests are drained through the existing
+//! `request_queue`, so `--initial-request-delay` still paces the bus.
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use knxkit::core::address::GroupAddress;
+
+use crate::cli::CLI;
+
+struct Entry {
+    address: GroupAddress,
+    interval: Duration,
+    next_due: Instant,
+}
+
+/// Tracks which groups are due for a refresh.
+pub struct Poller {
+    entries: Vec<Entry>,
+}
+
+impl Poller {
+    /// Builds a poller from the configured groups, or `None` when nothing is
+    /// scheduled (no `--poll-interval` default and no per-group interval).
+    pub fn new() -> Option<Self> {
+        let now = Instant::now();
+
+        let entries: Vec<Entry> = CLI
+            .source()
+            .groups()
+            .into_iter()
+            .filter_map(|group| {
+                let interval = CLI
+                    .source()
+                    .group_poll_interval(group.address)
+                    .or(CLI.poll_interval)?;
+
+                // never poll faster than the minimum bus spacing
+                let interval = interval.max(CLI.initial_request_delay);
+
+                Some(Entry {
+                    address: group.address,
+                    interval,
+                    next_due: now + interval,
+                })
+            })
+            .collect();
+
+        (!entries.is_empty()).then_some(Self { entries })
+    }
+
+    /// Instant at which the next group falls due.
+    pub fn next_due(&self) -> Instant {
+        self.entries
+            .iter()
+            .map(|entry| entry.next_due)
+            .min()
+            .expect("poller is never empty")
+    }
+
+    /// Returns the groups that are due at `now` and reschedules them.
+    pub fn take_due(&mut self, now: Instant) -> Vec<GroupAddress> {
+        let mut due = Vec::new();
+
+        for entry in &mut self.entries {
+            if entry.next_due <= now {
+                due.push(entry.address);
+                entry.next_due = now + entry.interval;
+            }
+        }
+
+        due
+    }
+}